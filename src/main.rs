@@ -1,18 +1,40 @@
+use async_stream::stream;
 use axum::{
     async_trait,
-    extract::{ConnectInfo, FromRequestParts, Multipart, OriginalUri, Query},
-    http::{request::Parts, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    body::{Body, Bytes},
+    extract::{ConnectInfo, FromRequestParts, Multipart, OriginalUri, Path, Query, Request},
+    http::{header::LOCATION, request::Parts, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
     response::{IntoResponse, Response},
-    routing::{delete, get, patch, post, put},
+    routing::{delete, get, patch, post, put, MethodRouter, Route},
     Form, Json, Router,
 };
 use axum_auth::{AuthBasic, AuthBearer};
+use axum_extra::{
+    headers::{
+        authorization::{Basic, Bearer as BearerScheme},
+        Accept, Authorization, ContentType, Host, UserAgent,
+    },
+    TypedHeader,
+};
 use axum_macros::debug_handler;
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine as _,
+};
+use hmac::{Hmac, Mac};
+use rand::Rng;
 use serde::ser::{SerializeMap, Serializer};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use sha2::Sha256;
 use std::collections::HashMap;
+use std::convert::Infallible;
+use std::env;
 use std::net::SocketAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::interval;
+use tower::{Layer, Service};
+use tower_http::compression::CompressionLayer;
 
 macro_rules! extract_from_request {
     ($parts:expr, $state:expr, $extractor:ident) => {
@@ -32,6 +54,7 @@ where
         let method = extract_from_request!(parts, state, Method);
         let args: Query<HashMap<String, String>> = extract_from_request!(parts, state, Query);
         let headers = extract_from_request!(parts, state, HeaderMap);
+        let parsed_headers = extract_from_request!(parts, state, ParsedHeaders);
         let url = extract_from_request!(parts, state, OriginalUri);
         let origin: ConnectInfo<SocketAddr> = extract_from_request!(parts, state, ConnectInfo);
         Ok(CommonRequestParts::new(
@@ -39,6 +62,7 @@ where
             url,
             method,
             headers,
+            parsed_headers,
             Some(args),
         ))
     }
@@ -50,11 +74,13 @@ impl CommonRequestParts {
         url: OriginalUri,
         method: Method,
         headers: HeaderMap,
+        parsed_headers: ParsedHeaders,
         params: Option<Query<HashMap<String, String>>>,
     ) -> Self {
         let Query(params) = params.unwrap_or_default();
         Self {
             headers: MyHeaderMap(headers.clone()),
+            parsed_headers,
             args: params,
             method: method.to_string(),
             url: url.to_string(),
@@ -63,6 +89,66 @@ impl CommonRequestParts {
     }
 }
 
+/// A structured view of the handful of headers clients care about
+/// most, parsed via `axum-extra`'s `TypedHeader`. Always sits next to the raw
+/// `headers` multimap on `CommonRequestParts` rather than replacing it, since
+/// the raw map is still needed for header names this crate doesn't parse.
+/// Any header that's missing or fails to parse serializes as `null`.
+#[derive(Serialize, Default)]
+struct ParsedHeaders {
+    host: Option<String>,
+    user_agent: Option<String>,
+    content_type: Option<String>,
+    authorization: Option<String>,
+    accept: Option<String>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ParsedHeaders
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let host = TypedHeader::<Host>::from_request_parts(parts, state)
+            .await
+            .ok()
+            .map(|TypedHeader(host)| host.to_string());
+        let user_agent = TypedHeader::<UserAgent>::from_request_parts(parts, state)
+            .await
+            .ok()
+            .map(|TypedHeader(user_agent)| user_agent.to_string());
+        let content_type = TypedHeader::<ContentType>::from_request_parts(parts, state)
+            .await
+            .ok()
+            .map(|TypedHeader(content_type)| content_type.to_string());
+        let authorization = match TypedHeader::<Authorization<BearerScheme>>::from_request_parts(
+            parts, state,
+        )
+        .await
+        {
+            Ok(TypedHeader(auth)) => Some(format!("Bearer {}", auth.token())),
+            Err(_) => TypedHeader::<Authorization<Basic>>::from_request_parts(parts, state)
+                .await
+                .ok()
+                .map(|TypedHeader(auth)| format!("Basic {}:{}", auth.username(), auth.password())),
+        };
+        let accept = TypedHeader::<Accept>::from_request_parts(parts, state)
+            .await
+            .ok()
+            .map(|TypedHeader(accept)| accept.to_string());
+
+        Ok(ParsedHeaders {
+            host,
+            user_agent,
+            content_type,
+            authorization,
+            accept,
+        })
+    }
+}
+
 #[derive(Serialize)]
 struct GetBasicAuthResponse {
     common_request_parts: CommonRequestParts,
@@ -74,7 +160,73 @@ struct GetBasicAuthResponse {
 struct GetBearerAuthResponse {
     common_request_parts: CommonRequestParts,
     authenticated: bool,
-    token: String,
+    claims: Claims,
+}
+
+/// The claims we expect a client-signed bearer token to carry. Mirrors the
+/// handful of registered JWT claims most real services actually check.
+#[derive(Debug, Deserialize, Serialize)]
+struct Claims {
+    sub: String,
+    exp: i64,
+    iss: String,
+}
+
+/// A bearer token that has been verified as a correctly-signed, non-expired
+/// HMAC-SHA256 JWT. Unlike `AuthBearer`, which just hands back whatever
+/// string the client sent, pulling this out of a handler's arguments proves
+/// the token was actually signed with `JWT_SECRET`.
+struct VerifiedJwt(Claims);
+
+fn bearer_unauthorized() -> Response {
+    let mut headers = HeaderMap::new();
+    headers.insert("WWW-Authenticate", HeaderValue::from_static("Bearer"));
+    (headers, StatusCode::UNAUTHORIZED).into_response()
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for VerifiedJwt
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let AuthBearer(token) = extract_from_request!(parts, state, AuthBearer);
+
+        let segments: Vec<&str> = token.split('.').collect();
+        let [header_b64, payload_b64, signature_b64] = match segments[..] {
+            [h, p, s] => [h, p, s],
+            _ => return Err(bearer_unauthorized()),
+        };
+
+        let secret = env::var("JWT_SECRET").map_err(|_| bearer_unauthorized())?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+            .map_err(|_| bearer_unauthorized())?;
+        mac.update(format!("{header_b64}.{payload_b64}").as_bytes());
+
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature_b64)
+            .map_err(|_| bearer_unauthorized())?;
+        mac.verify_slice(&signature)
+            .map_err(|_| bearer_unauthorized())?;
+
+        let payload = URL_SAFE_NO_PAD
+            .decode(payload_b64)
+            .map_err(|_| bearer_unauthorized())?;
+        let claims: Claims =
+            serde_json::from_slice(&payload).map_err(|_| bearer_unauthorized())?;
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64;
+        if claims.exp < now {
+            return Err(bearer_unauthorized());
+        }
+
+        Ok(VerifiedJwt(claims))
+    }
 }
 
 #[derive(Serialize)]
@@ -92,7 +244,36 @@ struct PostJsonResponse {
 #[derive(Serialize)]
 struct PostFileResponse {
     common_request_parts: CommonRequestParts,
-    files: HashMap<String, String>,
+    files: HashMap<String, FileField>,
+    form: HashMap<String, String>,
+}
+
+/// A single uploaded file from a multipart form. `content` is the raw bytes
+/// echoed back as UTF-8 when that's lossless, or base64 otherwise, so binary
+/// uploads (images, archives) round-trip without panicking.
+#[derive(Serialize)]
+struct FileField {
+    file_name: Option<String>,
+    content_type: Option<String>,
+    size: usize,
+    content_encoding: &'static str,
+    content: String,
+}
+
+/// Attaches a `tower` layer to a single route's `MethodRouter`. The router in
+/// `main` is otherwise a flat list of `.route(...)` calls, so centralizing
+/// the `.layer(...)` call here means future cross-cutting concerns (tracing,
+/// timeouts) can be bolted onto one route the same way compression is below,
+/// without duplicating the bound at every call site.
+fn with_layer<L>(router: MethodRouter, layer: L) -> MethodRouter
+where
+    L: Layer<Route> + Clone + Send + Sync + 'static,
+    L::Service: Service<Request> + Clone + Send + 'static,
+    <L::Service as Service<Request>>::Response: IntoResponse + 'static,
+    <L::Service as Service<Request>>::Error: Into<Infallible> + 'static,
+    <L::Service as Service<Request>>::Future: Send + 'static,
+{
+    router.layer(layer)
 }
 
 #[tokio::main]
@@ -107,7 +288,48 @@ async fn main() {
         .route("/post/form", post(form_handler))
         .route("/post/file", post(post_file_handler))
         .route("/basic-auth/user/passwd", get(get_basic_auth_handler))
-        .route("/bearer", get(get_bearer_auth_handler));
+        .route("/bearer", get(get_bearer_auth_handler))
+        .route("/user-agent", get(user_agent_handler))
+        .route("/headers", get(headers_handler))
+        .route("/stream/:n", get(stream_handler))
+        .route("/stream-bytes/:n", get(stream_bytes_handler))
+        .route("/drip", get(drip_handler))
+        .route(
+            "/gzip",
+            with_layer(
+                get(gzip_handler),
+                CompressionLayer::new()
+                    .gzip(true)
+                    .deflate(false)
+                    .br(false)
+                    .zstd(false),
+            ),
+        )
+        .route(
+            "/deflate",
+            with_layer(
+                get(deflate_handler),
+                CompressionLayer::new()
+                    .deflate(true)
+                    .gzip(false)
+                    .br(false)
+                    .zstd(false),
+            ),
+        )
+        .route(
+            "/brotli",
+            with_layer(
+                get(brotli_handler),
+                CompressionLayer::new()
+                    .br(true)
+                    .gzip(false)
+                    .deflate(false)
+                    .zstd(false),
+            ),
+        )
+        .route("/status/:codes", get(status_handler))
+        .route("/redirect/:n", get(redirect_handler))
+        .route("/response-headers", get(response_headers_handler));
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
     axum::serve(
@@ -155,20 +377,51 @@ async fn post_json_handler(
 async fn post_file_handler(
     common_request_parts: CommonRequestParts,
     mut multipart: Multipart,
-) -> Json<PostFileResponse> {
-    let mut data_map = HashMap::new();
-    while let Some(field) = multipart.next_field().await.unwrap() {
-        let name = field.name().unwrap().to_string();
-        let data = field.bytes().await.unwrap();
-        data_map.insert(
-            name.clone(),
-            String::from_utf8(data.clone().to_vec()).unwrap(),
+) -> Result<Json<PostFileResponse>, Response> {
+    let mut files = HashMap::new();
+    let mut form = HashMap::new();
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|err| err.into_response())?
+    {
+        let Some(name) = field.name().map(str::to_string) else {
+            continue;
+        };
+        let file_name = field.file_name().map(str::to_string);
+        let content_type = field.content_type().map(str::to_string);
+
+        // A field without a filename is an ordinary form field, not a file upload.
+        if file_name.is_none() {
+            let text = field.text().await.map_err(|err| err.into_response())?;
+            form.insert(name, text);
+            continue;
+        }
+
+        let data = field.bytes().await.map_err(|err| err.into_response())?;
+        let (content, content_encoding) = match std::str::from_utf8(&data) {
+            Ok(text) => (text.to_string(), "utf-8"),
+            Err(_) => (STANDARD.encode(&data), "base64"),
+        };
+
+        files.insert(
+            name,
+            FileField {
+                file_name,
+                content_type,
+                size: data.len(),
+                content_encoding,
+                content,
+            },
         );
     }
-    Json(PostFileResponse {
+
+    Ok(Json(PostFileResponse {
         common_request_parts,
-        files: data_map,
-    })
+        files,
+        form,
+    }))
 }
 
 async fn get_basic_auth_handler(
@@ -205,22 +458,262 @@ async fn get_basic_auth_handler(
 
 async fn get_bearer_auth_handler(
     common_request_parts: CommonRequestParts,
-    AuthBearer(token): AuthBearer,
+    VerifiedJwt(claims): VerifiedJwt,
 ) -> Json<GetBearerAuthResponse> {
-    println!("token is {token}");
     Json(GetBearerAuthResponse {
         common_request_parts,
-        token: token,
         authenticated: true,
+        claims,
+    })
+}
+
+#[derive(Serialize)]
+struct UserAgentResponse {
+    user_agent: String,
+}
+
+/// Returns just the parsed `User-Agent`, like httpbin's `/user-agent`.
+async fn user_agent_handler(
+    TypedHeader(user_agent): TypedHeader<UserAgent>,
+) -> Json<UserAgentResponse> {
+    Json(UserAgentResponse {
+        user_agent: user_agent.to_string(),
+    })
+}
+
+#[derive(Serialize)]
+struct HeadersResponse {
+    headers: MyHeaderMap,
+}
+
+/// Returns the full raw header multimap, like httpbin's `/headers`.
+async fn headers_handler(headers: HeaderMap) -> Json<HeadersResponse> {
+    Json(HeadersResponse {
+        headers: MyHeaderMap(headers),
+    })
+}
+
+#[derive(Serialize)]
+struct GzipResponse {
+    common_request_parts: CommonRequestParts,
+    gzipped: bool,
+}
+
+/// Forces the response body to be gzip-compressed via the route's
+/// `CompressionLayer` (the client must send `Accept-Encoding: gzip`).
+async fn gzip_handler(common_request_parts: CommonRequestParts) -> Json<GzipResponse> {
+    Json(GzipResponse {
+        common_request_parts,
+        gzipped: true,
+    })
+}
+
+#[derive(Serialize)]
+struct DeflateResponse {
+    common_request_parts: CommonRequestParts,
+    deflated: bool,
+}
+
+/// Forces the response body to be deflate-compressed via the route's
+/// `CompressionLayer` (the client must send `Accept-Encoding: deflate`).
+async fn deflate_handler(common_request_parts: CommonRequestParts) -> Json<DeflateResponse> {
+    Json(DeflateResponse {
+        common_request_parts,
+        deflated: true,
+    })
+}
+
+#[derive(Serialize)]
+struct BrotliResponse {
+    common_request_parts: CommonRequestParts,
+    brotli: bool,
+}
+
+/// Forces the response body to be brotli-compressed via the route's
+/// `CompressionLayer` (the client must send `Accept-Encoding: br`).
+async fn brotli_handler(common_request_parts: CommonRequestParts) -> Json<BrotliResponse> {
+    Json(BrotliResponse {
+        common_request_parts,
+        brotli: true,
     })
 }
 
+/// Accepts a single status code or a comma-separated list (picking one at
+/// random when there's more than one) and returns a response with exactly
+/// that status, so clients can drive their response-handling paths.
+async fn status_handler(Path(codes): Path<String>) -> Response {
+    let codes: Vec<StatusCode> = codes
+        .split(',')
+        .filter_map(|code| code.trim().parse::<u16>().ok())
+        .filter_map(|code| StatusCode::from_u16(code).ok())
+        .collect();
+
+    let status = match codes.len() {
+        0 => return StatusCode::BAD_REQUEST.into_response(),
+        1 => codes[0],
+        len => codes[rand::thread_rng().gen_range(0..len)],
+    };
+
+    (
+        status,
+        status.canonical_reason().unwrap_or_default().to_string(),
+    )
+        .into_response()
+}
+
+#[derive(Deserialize)]
+struct RedirectParams {
+    #[serde(default)]
+    absolute: bool,
+}
+
+/// Issues a 302 redirect to `/redirect/{n-1}`, chaining down to `/get`
+/// (`/redirect/1` redirects straight to `/get`, so `/redirect/n` takes `n`
+/// hops), so clients can test following a run of redirects.
+async fn redirect_handler(
+    Path(n): Path<u32>,
+    Query(params): Query<RedirectParams>,
+) -> Response {
+    let next = if n <= 1 {
+        "/get".to_string()
+    } else {
+        format!("/redirect/{}", n - 1)
+    };
+    let location = if params.absolute {
+        format!("http://localhost:3000{next}")
+    } else {
+        next
+    };
+
+    match HeaderValue::from_str(&location) {
+        Ok(value) => {
+            let mut headers = HeaderMap::new();
+            headers.insert(LOCATION, value);
+            (StatusCode::FOUND, headers).into_response()
+        }
+        Err(_) => StatusCode::BAD_REQUEST.into_response(),
+    }
+}
+
+#[derive(Serialize)]
+struct ResponseHeadersBody {
+    common_request_parts: CommonRequestParts,
+    #[serde(flatten)]
+    echoed: HashMap<String, String>,
+}
+
+/// Reflects arbitrary query params back as real response headers (as well as
+/// in the JSON body), so clients can assert on headers the server sets.
+async fn response_headers_handler(
+    common_request_parts: CommonRequestParts,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let mut headers = HeaderMap::new();
+    for (key, value) in &params {
+        if let (Ok(name), Ok(value)) =
+            (HeaderName::from_bytes(key.as_bytes()), HeaderValue::from_str(value))
+        {
+            headers.insert(name, value);
+        }
+    }
+
+    let body = Json(ResponseHeadersBody {
+        common_request_parts,
+        echoed: params,
+    });
+
+    (headers, body).into_response()
+}
+
+#[derive(Serialize)]
+struct StreamLine<'a> {
+    common_request_parts: &'a CommonRequestParts,
+    id: u32,
+}
+
+/// Emits `n` newline-delimited JSON objects, one per line, flushing as each
+/// one is produced so clients can exercise incremental/chunked reads.
+async fn stream_handler(
+    common_request_parts: CommonRequestParts,
+    Path(n): Path<u32>,
+) -> Response {
+    let body_stream = stream! {
+        for id in 0..n {
+            let line = StreamLine {
+                common_request_parts: &common_request_parts,
+                id,
+            };
+            match serde_json::to_vec(&line) {
+                Ok(mut bytes) => {
+                    bytes.push(b'\n');
+                    yield Ok::<_, Infallible>(Bytes::from(bytes));
+                }
+                Err(_) => break,
+            }
+        }
+    };
+    Body::from_stream(body_stream).into_response()
+}
+
+#[derive(Deserialize)]
+struct StreamBytesParams {
+    chunk_size: Option<usize>,
+}
+
+/// Streams `n` random bytes, split into `chunk_size`-sized writes (default
+/// 1024), so clients can test reading a body that arrives in pieces.
+async fn stream_bytes_handler(
+    Path(n): Path<usize>,
+    Query(params): Query<StreamBytesParams>,
+) -> Response {
+    let chunk_size = params.chunk_size.unwrap_or(1024).max(1);
+    let body_stream = stream! {
+        let mut remaining = n;
+        let mut rng = rand::thread_rng();
+        while remaining > 0 {
+            let this_chunk = remaining.min(chunk_size);
+            let bytes: Vec<u8> = (0..this_chunk).map(|_| rng.gen()).collect();
+            remaining -= this_chunk;
+            yield Ok::<_, Infallible>(Bytes::from(bytes));
+        }
+    };
+    Body::from_stream(body_stream).into_response()
+}
+
+#[derive(Deserialize)]
+struct DripParams {
+    numbytes: Option<u64>,
+    duration: Option<u64>,
+    delay: Option<u64>,
+}
+
+/// Trickles `numbytes` bytes out over `duration` seconds, optionally waiting
+/// `delay` seconds before the first byte, to exercise slow/incremental reads.
+async fn drip_handler(Query(params): Query<DripParams>) -> Response {
+    let numbytes = params.numbytes.unwrap_or(10).max(1);
+    let duration = params.duration.unwrap_or(2).max(1);
+    let delay = params.delay.unwrap_or(0);
+
+    let body_stream = stream! {
+        if delay > 0 {
+            tokio::time::sleep(Duration::from_secs(delay)).await;
+        }
+        let mut ticker = interval(Duration::from_secs_f64(duration as f64 / numbytes as f64));
+        for _ in 0..numbytes {
+            ticker.tick().await;
+            yield Ok::<_, Infallible>(Bytes::from_static(b"*"));
+        }
+    };
+    Body::from_stream(body_stream).into_response()
+}
+
 #[derive(Serialize)]
 struct CommonRequestParts {
     method: String,
     /// The URL parameters
     args: HashMap<String, String>,
     headers: MyHeaderMap,
+    parsed_headers: ParsedHeaders,
     url: String,
     origin: String,
 }